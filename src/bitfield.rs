@@ -1,12 +1,12 @@
 //! Definitions of bitfield things for hashmap neighbourhoods.
 use std::iter::Iterator;
-use std::ops::{BitAnd, BitOr, Shr};
+use std::ops::{BitAnd, BitOr};
 
 /// A bit field trait for use in hashmap buckets. See the `bitfield` method of `BiMapBuilder` for
 /// more information.
 pub trait BitField: BitAnd<Output = Self> + BitOr<Output = Self> + Copy + Sized {
     /// See the documentation for the `iter` function.
-    type Iter: Iterator<Item = usize>;
+    type Iter: Iterator<Item = usize> + DoubleEndedIterator;
 
     /// Should return a constant value describing how big the bitfield of this type is.
     fn size() -> usize;
@@ -21,45 +21,86 @@ pub trait BitField: BitAnd<Output = Self> + BitOr<Output = Self> + Copy + Sized
     /// bitfield that have 1s in them, in order from least significant to most significant.
     fn iter(&self) -> Self::Iter;
 
+    /// Return an iterator over the same indexes as `iter`, but in the opposite order: most
+    /// significant to least significant. Handy for hopscotch displacement searches, which often
+    /// want the highest occupied (or lowest free) slot in a neighbourhood.
+    fn iter_rev(&self) -> std::iter::Rev<Self::Iter> {
+        self.iter().rev()
+    }
+
     /// Is the bitfield currently full?
     fn full(&self) -> bool;
+
+    /// How many bits are currently set in the bitfield.
+    fn count_ones(&self) -> usize;
+
+    /// The index of the lowest set bit, or `None` if the bitfield is empty.
+    fn lowest_set_index(&self) -> Option<usize>;
+
+    /// Return a copy of the bitfield with its lowest set bit cleared.
+    fn without_lowest(self) -> Self;
+
+    /// The index of the highest set bit, or `None` if the bitfield is empty.
+    fn highest_set_index(&self) -> Option<usize>;
+
+    /// Return a copy of the bitfield with its highest set bit cleared.
+    fn without_highest(self) -> Self;
 }
 
 mod private {
     use super::{BitField, BitFieldIterator};
 
-    use std::ops::{BitAnd, BitOr, Not, Shl, Shr};
+    use std::ops::{BitAnd, BitOr, Not, Shl, Sub};
 
     /// Helper trait to reduce code duplication when implementing Bitfield for integer types.
     pub trait BitSized {
         /// Returns how many bits are in the type.
         fn size() -> usize;
-    }
 
-    impl BitSized for u8 {
-        fn size() -> usize {
-            8
-        }
-    }
+        /// Returns how many bits are set in `self`.
+        fn count_ones(self) -> usize;
 
-    impl BitSized for u16 {
-        fn size() -> usize {
-            16
-        }
-    }
+        /// Returns the number of trailing zero bits in `self`.
+        fn trailing_zeros(self) -> usize;
 
-    impl BitSized for u32 {
-        fn size() -> usize {
-            32
-        }
+        /// Returns the number of leading zero bits in `self`.
+        fn leading_zeros(self) -> usize;
+
+        /// Returns `self` negated using wrapping arithmetic.
+        fn wrapping_neg(self) -> Self;
     }
 
-    impl BitSized for u64 {
-        fn size() -> usize {
-            64
-        }
+    macro_rules! impl_bit_sized {
+        ($t:ty, $size:expr) => {
+            impl BitSized for $t {
+                fn size() -> usize {
+                    $size
+                }
+
+                fn count_ones(self) -> usize {
+                    <$t>::count_ones(self) as usize
+                }
+
+                fn trailing_zeros(self) -> usize {
+                    <$t>::trailing_zeros(self) as usize
+                }
+
+                fn leading_zeros(self) -> usize {
+                    <$t>::leading_zeros(self) as usize
+                }
+
+                fn wrapping_neg(self) -> Self {
+                    <$t>::wrapping_neg(self)
+                }
+            }
+        };
     }
 
+    impl_bit_sized!(u8, 8);
+    impl_bit_sized!(u16, 16);
+    impl_bit_sized!(u32, 32);
+    impl_bit_sized!(u64, 64);
+
     impl<T> BitField for T
     where
         T: BitSized
@@ -68,7 +109,7 @@ mod private {
             + Eq
             + Not<Output = T>
             + Shl<usize, Output = T>
-            + Shr<usize, Output = T>
+            + Sub<Output = T>
             + From<u8>
             + Copy,
     {
@@ -87,38 +128,419 @@ mod private {
         }
 
         fn iter(&self) -> Self::Iter {
-            BitFieldIterator(*self, 0)
+            BitFieldIterator(*self)
         }
 
         fn full(&self) -> bool {
             *self == Self::one_at(0) | Self::zero_at(0)
         }
+
+        fn count_ones(&self) -> usize {
+            <T as BitSized>::count_ones(*self)
+        }
+
+        fn lowest_set_index(&self) -> Option<usize> {
+            if *self == Self::from(0) {
+                None
+            } else {
+                let lowest = *self & <T as BitSized>::wrapping_neg(*self);
+                Some(<T as BitSized>::trailing_zeros(lowest))
+            }
+        }
+
+        fn without_lowest(self) -> Self {
+            self & (self - Self::from(1))
+        }
+
+        fn highest_set_index(&self) -> Option<usize> {
+            if *self == Self::from(0) {
+                None
+            } else {
+                Some(Self::size() - 1 - <T as BitSized>::leading_zeros(*self))
+            }
+        }
+
+        fn without_highest(self) -> Self {
+            match self.highest_set_index() {
+                Some(index) => self & Self::zero_at(index),
+                None => self,
+            }
+        }
     }
 }
 
-/// An iterator over the active bits in a bitfield.
-pub struct BitFieldIterator<T>(T, usize);
+/// An iterator over the active bits in a bitfield, jumping directly to each set bit via
+/// `lowest_set_index`/`without_lowest` rather than scanning one position at a time.
+pub struct BitFieldIterator<T>(T);
 
 impl<T> Iterator for BitFieldIterator<T>
 where
-    T: Eq + BitAnd<Output = T> + Shr<usize, Output = T> + From<u8> + Copy,
+    T: BitField,
 {
     type Item = usize;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let &mut BitFieldIterator(ref mut bitfield, ref mut index) = self;
+        let &mut BitFieldIterator(ref mut bitfield) = self;
+
+        let index = bitfield.lowest_set_index()?;
+        *bitfield = bitfield.without_lowest();
+        Some(index)
+    }
+}
+
+impl<T> DoubleEndedIterator for BitFieldIterator<T>
+where
+    T: BitField,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let &mut BitFieldIterator(ref mut bitfield) = self;
+
+        let index = bitfield.highest_set_index()?;
+        *bitfield = bitfield.without_highest();
+        Some(index)
+    }
+}
+
+/// A fixed-size array of 64-bit words, usable as a `BitField` with up to `N * 64` slots. Slot `i`
+/// lives in word `i / 64` at bit `i % 64`. Pick this over the integer impls above when a
+/// hopscotch neighbourhood needs more than 64 slots.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Words<const N: usize>(pub [u64; N]);
+
+impl<const N: usize> BitAnd for Words<N> {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        let mut words = [0u64; N];
+        for (word, (&lhs, &rhs)) in words.iter_mut().zip(self.0.iter().zip(rhs.0.iter())) {
+            *word = lhs & rhs;
+        }
+        Words(words)
+    }
+}
+
+impl<const N: usize> BitOr for Words<N> {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        let mut words = [0u64; N];
+        for (word, (&lhs, &rhs)) in words.iter_mut().zip(self.0.iter().zip(rhs.0.iter())) {
+            *word = lhs | rhs;
+        }
+        Words(words)
+    }
+}
+
+impl<const N: usize> BitField for Words<N> {
+    type Iter = BitFieldIterator<Words<N>>;
+
+    fn size() -> usize {
+        N * 64
+    }
+
+    fn one_at(index: usize) -> Self {
+        let mut words = [0u64; N];
+        words[index / 64] = 1 << (index % 64);
+        Words(words)
+    }
+
+    fn zero_at(index: usize) -> Self {
+        let mut words = [!0u64; N];
+        words[index / 64] = !(1 << (index % 64));
+        Words(words)
+    }
+
+    fn iter(&self) -> Self::Iter {
+        BitFieldIterator(*self)
+    }
+
+    fn full(&self) -> bool {
+        self.0.iter().all(|&word| word == !0)
+    }
+
+    fn count_ones(&self) -> usize {
+        self.0
+            .iter()
+            .map(|&word| u64::count_ones(word) as usize)
+            .sum()
+    }
+
+    fn lowest_set_index(&self) -> Option<usize> {
+        self.0
+            .iter()
+            .enumerate()
+            .find(|&(_, &word)| word != 0)
+            .map(|(i, &word)| i * 64 + word.trailing_zeros() as usize)
+    }
+
+    fn without_lowest(self) -> Self {
+        let mut words = self.0;
+        for word in words.iter_mut() {
+            if *word != 0 {
+                *word &= *word - 1;
+                break;
+            }
+        }
+        Words(words)
+    }
+
+    fn highest_set_index(&self) -> Option<usize> {
+        self.0
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|&(_, &word)| word != 0)
+            .map(|(i, &word)| i * 64 + 63 - word.leading_zeros() as usize)
+    }
+
+    fn without_highest(self) -> Self {
+        let mut words = self.0;
+        for word in words.iter_mut().rev() {
+            if *word != 0 {
+                let bit = 63 - word.leading_zeros() as usize;
+                *word &= !(1 << bit);
+                break;
+            }
+        }
+        Words(words)
+    }
+}
+
+/// Row-major bit-matrix storage for a whole neighbourhood table: a single `Vec<u64>` holding
+/// `rows * rowsize` words, `rowsize` words per row, so every bitfield in the table lives in one
+/// contiguous allocation instead of one per bucket.
+///
+/// # Examples
+///
+/// ```
+/// use bimap::bitfield::BitMatrix;
+///
+/// // One neighbourhood bitfield per bucket, 70 slots each, in one allocation.
+/// let mut neighbourhoods = BitMatrix::new(4, 70);
+/// neighbourhoods.row_mut(1).set(3);
+/// neighbourhoods.row_mut(1).set(69);
+///
+/// assert_eq!(neighbourhoods.row(1).iter().collect::<Vec<_>>(), vec![3, 69]);
+///
+/// // Clearing a whole row (e.g. on resize) touches only that row's words.
+/// neighbourhoods.row_mut(1).clear();
+/// assert_eq!(neighbourhoods.row(1).count_ones(), 0);
+/// ```
+pub struct BitMatrix {
+    words: Vec<u64>,
+    cols: usize,
+    rowsize: usize,
+}
+
+impl BitMatrix {
+    /// Build a new, all-zero matrix with the given number of rows and columns.
+    ///
+    /// Panics if `cols` is zero.
+    pub fn new(rows: usize, cols: usize) -> Self {
+        assert!(cols > 0, "a BitMatrix needs at least one column");
+
+        let rowsize = cols.div_ceil(64);
+        BitMatrix {
+            words: vec![0; rows * rowsize],
+            cols,
+            rowsize,
+        }
+    }
 
-        if *bitfield == T::from(0) {
-            None
+    /// The number of columns (bits) in each row.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// The number of rows in the matrix.
+    pub fn rows(&self) -> usize {
+        self.words.len() / self.rowsize
+    }
+
+    /// Borrow a single row of the matrix.
+    pub fn row(&self, row: usize) -> BitMatrixRow<'_> {
+        let start = row * self.rowsize;
+        BitMatrixRow {
+            words: &self.words[start..start + self.rowsize],
+            cols: self.cols,
+        }
+    }
+
+    /// Mutably borrow a single row of the matrix.
+    pub fn row_mut(&mut self, row: usize) -> BitMatrixRowMut<'_> {
+        let start = row * self.rowsize;
+        BitMatrixRowMut {
+            words: &mut self.words[start..start + self.rowsize],
+            cols: self.cols,
+        }
+    }
+}
+
+/// A read-only view of a single row of a `BitMatrix`.
+#[derive(Clone, Copy)]
+pub struct BitMatrixRow<'a> {
+    words: &'a [u64],
+    cols: usize,
+}
+
+impl<'a> BitMatrixRow<'a> {
+    /// The bitmask of valid bits in the final word of the row, to ignore padding past `cols`.
+    fn tail_mask(&self) -> u64 {
+        let tail_bits = self.cols - (self.words.len() - 1) * 64;
+        if tail_bits >= 64 {
+            !0
         } else {
-            while T::from(1) & *bitfield == T::from(0) {
-                *bitfield = *bitfield >> 1;
-                *index += 1;
+            (1 << tail_bits) - 1
+        }
+    }
+
+    /// Is every valid bit in the row set?
+    pub fn full(&self) -> bool {
+        let (last, rest) = self
+            .words
+            .split_last()
+            .expect("a matrix row always has at least one word");
+        rest.iter().all(|&word| word == !0) && *last == self.tail_mask()
+    }
+
+    /// How many bits are set in the row.
+    pub fn count_ones(&self) -> usize {
+        self.words
+            .iter()
+            .map(|&word| u64::count_ones(word) as usize)
+            .sum()
+    }
+
+    /// Iterate the indexes of the set bits in the row, in order from least significant to most
+    /// significant, jumping word to word the same way `BitFieldIterator` jumps bit to bit.
+    pub fn iter(&self) -> BitMatrixRowIter<'a> {
+        BitMatrixRowIter {
+            words: self.words,
+            front: 0,
+            back: self.cols,
+        }
+    }
+}
+
+/// A mutable view of a single row of a `BitMatrix`.
+pub struct BitMatrixRowMut<'a> {
+    words: &'a mut [u64],
+    cols: usize,
+}
+
+impl<'a> BitMatrixRowMut<'a> {
+    /// Reborrow this row as a read-only view.
+    pub fn as_row(&self) -> BitMatrixRow<'_> {
+        BitMatrixRow {
+            words: self.words,
+            cols: self.cols,
+        }
+    }
+
+    /// Set every word in the row to zero.
+    pub fn clear(&mut self) {
+        for word in self.words.iter_mut() {
+            *word = 0;
+        }
+    }
+
+    /// Set a single bit in the row.
+    pub fn set(&mut self, index: usize) {
+        assert!(
+            index < self.cols,
+            "index {} out of bounds for row of {} cols",
+            index,
+            self.cols
+        );
+        self.words[index / 64] |= 1 << (index % 64);
+    }
+
+    /// Clear a single bit in the row.
+    pub fn unset(&mut self, index: usize) {
+        assert!(
+            index < self.cols,
+            "index {} out of bounds for row of {} cols",
+            index,
+            self.cols
+        );
+        self.words[index / 64] &= !(1 << (index % 64));
+    }
+
+    /// OR another row's bits into this one, word by word.
+    ///
+    /// Panics if `other` doesn't come from a matrix with the same number of columns.
+    pub fn or_with(&mut self, other: BitMatrixRow<'_>) {
+        assert_eq!(
+            self.cols, other.cols,
+            "cannot OR rows from matrices with different column counts"
+        );
+        for (word, &other_word) in self.words.iter_mut().zip(other.words) {
+            *word |= other_word;
+        }
+    }
+}
+
+/// An iterator over the set bit indexes in a `BitMatrixRow`, borrowing the row's words directly
+/// rather than copying them. Tracks the not-yet-yielded bit range as `[front, back)` and jumps
+/// directly to each set bit via `trailing_zeros`/`leading_zeros`, the same trick
+/// `BitFieldIterator` uses for a single word.
+pub struct BitMatrixRowIter<'a> {
+    words: &'a [u64],
+    front: usize,
+    back: usize,
+}
+
+impl<'a> Iterator for BitMatrixRowIter<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.front < self.back {
+            let word_index = self.front / 64;
+            let shifted = self.words[word_index] >> (self.front % 64);
+
+            if shifted == 0 {
+                self.front = (word_index + 1) * 64;
+                continue;
             }
-            *bitfield = *bitfield >> 1;
-            *index += 1;
-            Some(*index - 1)
+
+            let found = self.front + shifted.trailing_zeros() as usize;
+            if found >= self.back {
+                self.front = self.back;
+                return None;
+            }
+            self.front = found + 1;
+            return Some(found);
+        }
+        None
+    }
+}
+
+impl<'a> DoubleEndedIterator for BitMatrixRowIter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.back > self.front {
+            let word_index = (self.back - 1) / 64;
+            let valid_bits = self.back - word_index * 64;
+            let word = self.words[word_index];
+            let masked = if valid_bits >= 64 {
+                word
+            } else {
+                word & ((1 << valid_bits) - 1)
+            };
+
+            if masked == 0 {
+                self.back = word_index * 64;
+                continue;
+            }
+
+            let found = word_index * 64 + 63 - masked.leading_zeros() as usize;
+            if found < self.front {
+                self.back = self.front;
+                return None;
+            }
+            self.back = found;
+            return Some(found);
         }
+        None
     }
 }
 
@@ -157,4 +579,101 @@ mod test {
                 .fold(0, |x, y| x + y)
         }
     }
+
+    quickcheck! {
+        fn count_ones_matches_iterator_length(input: u32) -> bool {
+            BitField::count_ones(&input) == input.iter().count()
+        }
+    }
+
+    quickcheck! {
+        fn iter_rev_is_iter_reversed(input: u32) -> bool {
+            let forward: Vec<_> = input.iter().collect();
+            let backward: Vec<_> = input.iter_rev().collect();
+            forward.into_iter().rev().eq(backward)
+        }
+    }
+
+    #[test]
+    fn words_size_is_word_count_times_64() {
+        assert_eq!(super::Words::<3>::size(), 192);
+    }
+
+    #[test]
+    fn words_one_at_sets_a_single_bit_in_the_right_word() {
+        let field = super::Words::<2>::one_at(70);
+        assert_eq!(field.0, [0, 1 << (70 - 64)]);
+        assert_eq!(field.iter().collect::<Vec<_>>(), vec![70]);
+    }
+
+    #[test]
+    fn words_full_requires_every_word_to_be_all_ones() {
+        assert!(!super::Words([!0u64, 0]).full());
+        assert!(super::Words([!0u64, !0u64]).full());
+    }
+
+    #[test]
+    fn words_iter_rev_crosses_a_word_boundary() {
+        let field = super::Words::<2>::one_at(3) | super::Words::<2>::one_at(70);
+        assert_eq!(field.iter_rev().collect::<Vec<_>>(), vec![70, 3]);
+
+        let mut iter = field.iter();
+        assert_eq!(iter.next_back(), Some(70));
+        assert_eq!(iter.next(), Some(3));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn bit_matrix_rowsize_rounds_up_to_a_whole_word() {
+        let matrix = super::BitMatrix::new(4, 70);
+        assert_eq!(matrix.rowsize, 2);
+        assert_eq!(matrix.rows(), 4);
+        assert_eq!(matrix.cols(), 70);
+    }
+
+    #[test]
+    fn bit_matrix_rows_are_independent() {
+        let mut matrix = super::BitMatrix::new(2, 70);
+        matrix.row_mut(0).set(3);
+        matrix.row_mut(1).set(69);
+
+        assert_eq!(matrix.row(0).iter().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(matrix.row(1).iter().collect::<Vec<_>>(), vec![69]);
+    }
+
+    #[test]
+    fn bit_matrix_full_ignores_padding_past_cols() {
+        let mut matrix = super::BitMatrix::new(1, 70);
+        for index in 0..70 {
+            matrix.row_mut(0).set(index);
+        }
+
+        assert!(matrix.row(0).full());
+        assert_eq!(matrix.row(0).count_ones(), 70);
+    }
+
+    #[test]
+    fn bit_matrix_row_iter_supports_next_back() {
+        let mut matrix = super::BitMatrix::new(1, 70);
+        matrix.row_mut(0).set(3);
+        matrix.row_mut(0).set(69);
+
+        let mut iter = matrix.row(0).iter();
+        assert_eq!(iter.next_back(), Some(69));
+        assert_eq!(iter.next(), Some(3));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn bit_matrix_or_with_combines_rows() {
+        let mut dest = super::BitMatrix::new(1, 70);
+        dest.row_mut(0).set(3);
+
+        let mut src = super::BitMatrix::new(1, 70);
+        src.row_mut(0).set(69);
+
+        dest.row_mut(0).or_with(src.row(0));
+
+        assert_eq!(dest.row(0).iter().collect::<Vec<_>>(), vec![3, 69]);
+    }
 }